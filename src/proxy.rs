@@ -1,28 +1,219 @@
-use std::collections::{HashMap, HashSet};
-use std::io;
-use std::{cell::RefCell, net::SocketAddr, usize};
+use std::cmp::Reverse;
+use std::collections::{BinaryHeap, HashMap, HashSet};
+use std::io::{self, Read, Write};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+use std::{
+  cell::{Cell, RefCell},
+  net::SocketAddr,
+  usize,
+};
 
 use crypto::{digest::Digest, sha2::Sha256};
-use mio::{net::TcpListener, unix::UnixReady, Events, Poll, PollOpt, Ready, Token};
+use mio::{
+  net::{TcpListener, TcpStream},
+  unix::UnixReady,
+  Evented, Events, Poll, PollOpt, Ready, Registration, SetReadiness, Token,
+};
+use mio_uds::{UnixListener, UnixStream};
 use pool::DcPool;
 use pump::Pump;
+use signal_hook::iterator::Signals;
+use signal_hook::{SIGHUP, SIGINT, SIGTERM};
 use slab::Slab;
 
 const MAX_PUMPS: usize = 1024 * 1024;
 const ROOT_TOKEN: Token = Token(<usize>::max_value() - 1);
+const WAKE_TOKEN: Token = Token(<usize>::max_value() - 2);
+
+// Backpressure watermarks, in bytes queued for write on a single pump.
+const HIGH_WATERMARK: usize = 1024 * 1024;
+const LOW_WATERMARK: usize = 128 * 1024;
+// Global cap across all pumps before new connections are refused.
+const MAX_BUFFERED_BYTES: usize = 256 * 1024 * 1024;
+
+/// Where the proxy accepts client connections from.
+pub enum BindAddr {
+  Tcp(SocketAddr),
+  Unix(PathBuf),
+}
+
+/// The accept-side listener: either a plain TCP socket or a Unix-domain
+/// socket for deployments fronted by a local reverse proxy.
+enum Listener {
+  Tcp(TcpListener),
+  Unix(UnixListener),
+}
+
+impl Listener {
+  fn bind(addr: &BindAddr) -> Listener {
+    match addr {
+      BindAddr::Tcp(addr) => Listener::Tcp(TcpListener::bind(addr).expect("Failed to bind")),
+      BindAddr::Unix(path) => {
+        // A stale socket file left behind by an ungraceful exit (mio_uds
+        // doesn't unlink on drop) would otherwise make bind() fail with
+        // EADDRINUSE on every restart.
+        if let Err(e) = std::fs::remove_file(path) {
+          if e.kind() != io::ErrorKind::NotFound {
+            panic!("failed to remove stale socket {:?}: {}", path, e);
+          }
+        }
+        Listener::Unix(UnixListener::bind(path).expect("Failed to bind"))
+      }
+    }
+  }
+
+  fn accept(&self) -> io::Result<(ClientSock, Option<SocketAddr>)> {
+    match self {
+      Listener::Tcp(sock) => {
+        let (sock, addr) = sock.accept()?;
+        Ok((ClientSock::Tcp(sock), Some(addr)))
+      }
+      Listener::Unix(sock) => match sock.accept()? {
+        Some((sock, _)) => Ok((ClientSock::Unix(sock), None)),
+        None => Err(io::Error::from(io::ErrorKind::WouldBlock)),
+      },
+    }
+  }
+}
+
+impl Evented for Listener {
+  fn register(
+    &self,
+    poll: &Poll,
+    token: Token,
+    interest: Ready,
+    opts: PollOpt,
+  ) -> io::Result<()> {
+    match self {
+      Listener::Tcp(sock) => sock.register(poll, token, interest, opts),
+      Listener::Unix(sock) => sock.register(poll, token, interest, opts),
+    }
+  }
+
+  fn reregister(
+    &self,
+    poll: &Poll,
+    token: Token,
+    interest: Ready,
+    opts: PollOpt,
+  ) -> io::Result<()> {
+    match self {
+      Listener::Tcp(sock) => sock.reregister(poll, token, interest, opts),
+      Listener::Unix(sock) => sock.reregister(poll, token, interest, opts),
+    }
+  }
+
+  fn deregister(&self, poll: &Poll) -> io::Result<()> {
+    match self {
+      Listener::Tcp(sock) => sock.deregister(poll),
+      Listener::Unix(sock) => sock.deregister(poll),
+    }
+  }
+}
+
+/// An accepted client connection, TCP or Unix-domain, as handed to `Pump`.
+pub enum ClientSock {
+  Tcp(TcpStream),
+  Unix(UnixStream),
+}
+
+impl Read for ClientSock {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    match self {
+      ClientSock::Tcp(sock) => sock.read(buf),
+      ClientSock::Unix(sock) => sock.read(buf),
+    }
+  }
+}
+
+impl Write for ClientSock {
+  fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+    match self {
+      ClientSock::Tcp(sock) => sock.write(buf),
+      ClientSock::Unix(sock) => sock.write(buf),
+    }
+  }
+
+  fn flush(&mut self) -> io::Result<()> {
+    match self {
+      ClientSock::Tcp(sock) => sock.flush(),
+      ClientSock::Unix(sock) => sock.flush(),
+    }
+  }
+}
+
+impl Evented for ClientSock {
+  fn register(
+    &self,
+    poll: &Poll,
+    token: Token,
+    interest: Ready,
+    opts: PollOpt,
+  ) -> io::Result<()> {
+    match self {
+      ClientSock::Tcp(sock) => sock.register(poll, token, interest, opts),
+      ClientSock::Unix(sock) => sock.register(poll, token, interest, opts),
+    }
+  }
+
+  fn reregister(
+    &self,
+    poll: &Poll,
+    token: Token,
+    interest: Ready,
+    opts: PollOpt,
+  ) -> io::Result<()> {
+    match self {
+      ClientSock::Tcp(sock) => sock.reregister(poll, token, interest, opts),
+      ClientSock::Unix(sock) => sock.reregister(poll, token, interest, opts),
+    }
+  }
+
+  fn deregister(&self, poll: &Poll) -> io::Result<()> {
+    match self {
+      ClientSock::Tcp(sock) => sock.deregister(poll),
+      ClientSock::Unix(sock) => sock.deregister(poll),
+    }
+  }
+}
 
 pub struct Server {
-  sock: TcpListener,
+  sock: Listener,
   poll: Poll,
   secret: Vec<u8>,
   pool: DcPool,
   pumps: Slab<RefCell<Pump>>,
   detached: HashSet<Token>,
   links: HashMap<Token, Token>,
+  conn_ids: HashMap<Token, u64>,
+  next_conn_id: u64,
+  idle_timeout: Duration,
+  deadlines: HashMap<Token, Instant>,
+  timer_wheel: BinaryHeap<Reverse<(Instant, Token)>>,
+  shutdown_grace: Duration,
+  draining: Arc<AtomicBool>,
+  wake_registration: Registration,
+  wake_readiness: SetReadiness,
+  proxy_protocol: bool,
+  buffered_bytes: Cell<usize>,
+  paused: RefCell<HashSet<Token>>,
+  dc_config_path: PathBuf,
+  reload_requested: Arc<AtomicBool>,
 }
 
 impl Server {
-  pub fn new(addr: SocketAddr, seed: &str) -> Server {
+  pub fn new(
+    addr: BindAddr,
+    seed: &str,
+    idle_timeout: Duration,
+    shutdown_grace: Duration,
+    proxy_protocol: bool,
+    dc_config_path: PathBuf,
+  ) -> Server {
     let mut sha = Sha256::new();
     let mut secret = vec![0u8; sha.output_bytes()];
 
@@ -30,15 +221,153 @@ impl Server {
     sha.result(&mut secret);
     secret.truncate(16);
 
+    let (wake_registration, wake_readiness) = Registration::new2();
+
     Server {
       secret,
-      pool: DcPool::new(),
+      pool: DcPool::from_config(&dc_config_path).expect("Failed to load DC pool config"),
       detached: HashSet::new(),
-      sock: TcpListener::bind(&addr).expect("Failed to bind"),
+      sock: Listener::bind(&addr),
       poll: Poll::new().expect("Failed to create Poll"),
       pumps: Slab::with_capacity(MAX_PUMPS),
       links: HashMap::new(),
+      conn_ids: HashMap::new(),
+      next_conn_id: 0,
+      idle_timeout,
+      deadlines: HashMap::new(),
+      timer_wheel: BinaryHeap::new(),
+      shutdown_grace,
+      draining: Arc::new(AtomicBool::new(false)),
+      wake_registration,
+      wake_readiness,
+      proxy_protocol,
+      buffered_bytes: Cell::new(0),
+      paused: RefCell::new(HashSet::new()),
+      dc_config_path,
+      reload_requested: Arc::new(AtomicBool::new(false)),
+    }
+  }
+
+  // The interest to register for `token`, with `readable` suppressed while
+  // its linked peer is backpressured (see `fan_out`/`fan_in`).
+  fn effective_interest(&self, token: Token, pump: &Pump) -> Ready {
+    if self.paused.borrow().contains(&token) {
+      pump.interest() - Ready::readable()
+    } else {
+      pump.interest()
+    }
+  }
+
+  // Lifts backpressure on `token` once its destination has drained below
+  // the low watermark, restoring its full interest.
+  fn resume(&self, token: Token) -> io::Result<()> {
+    if !self.paused.borrow_mut().remove(&token) {
+      return Ok(());
+    }
+
+    if let Some(pump) = self.pumps.get(token.0) {
+      let pump = pump.borrow();
+      trace!("backpressure released, resuming {:?}", token);
+      self.poll.reregister(
+        pump.sock(),
+        token,
+        pump.interest(),
+        PollOpt::edge() | PollOpt::oneshot(),
+      )?;
+    }
+
+    Ok(())
+  }
+
+  // Spawns a thread that waits on SIGTERM/SIGINT (start draining) and SIGHUP
+  // (reload the DC endpoint pool), and wakes the poll loop via
+  // `wake_readiness` so `run` acts on it without blocking on `poll`.
+  fn install_signal_handler(&self) -> io::Result<()> {
+    let draining = self.draining.clone();
+    let reload_requested = self.reload_requested.clone();
+    let set_readiness = self.wake_readiness.clone();
+    let signals = Signals::new(&[SIGTERM, SIGINT, SIGHUP])?;
+
+    thread::spawn(move || {
+      for signal in signals.forever() {
+        match signal {
+          SIGHUP => {
+            info!("received SIGHUP, reloading DC endpoints");
+            reload_requested.store(true, Ordering::SeqCst);
+          }
+          _ => {
+            info!("received signal {}, draining connections", signal);
+            draining.store(true, Ordering::SeqCst);
+          }
+        }
+        let _ = set_readiness.set_readiness(Ready::readable());
+      }
+    });
+
+    Ok(())
+  }
+
+  // Takes the counter by reference (rather than `&mut self`) so it can be
+  // called at sites that are already holding a borrow of a live `pumps`
+  // entry.
+  fn next_conn_id(next_conn_id: &mut u64) -> u64 {
+    *next_conn_id += 1;
+    *next_conn_id
+  }
+
+  // Records a fresh idle deadline for `token`. Takes the specific fields it
+  // needs (rather than `&mut self`) so it can be called at sites that are
+  // already holding a borrow of a live `pumps` entry.
+  fn touch(
+    deadlines: &mut HashMap<Token, Instant>,
+    timer_wheel: &mut BinaryHeap<Reverse<(Instant, Token)>>,
+    idle_timeout: Duration,
+    token: Token,
+  ) {
+    let deadline = Instant::now() + idle_timeout;
+    deadlines.insert(token, deadline);
+    timer_wheel.push(Reverse((deadline, token)));
+  }
+
+  // Pops lazily-invalidated entries (superseded by a later `touch`) off the
+  // heap and returns the next deadline still current in `deadlines`.
+  fn next_deadline(&mut self) -> Option<Instant> {
+    while let Some(&Reverse((deadline, token))) = self.timer_wheel.peek() {
+      match self.deadlines.get(&token) {
+        Some(&current) if current == deadline => return Some(deadline),
+        _ => {
+          self.timer_wheel.pop();
+        }
+      }
     }
+    None
+  }
+
+  fn sweep_idle(&mut self) -> io::Result<()> {
+    let now = Instant::now();
+    let mut expired = Vec::new();
+
+    while let Some(&Reverse((deadline, token))) = self.timer_wheel.peek() {
+      if deadline > now {
+        break;
+      }
+      self.timer_wheel.pop();
+      if let Some(&current) = self.deadlines.get(&token) {
+        if current == deadline {
+          expired.push(token);
+        }
+      }
+    }
+
+    for token in expired {
+      self.deadlines.remove(&token);
+      if self.pumps.get(token.0).is_some() {
+        info!("idle timeout, dropping: {:?}", token);
+        self.drop_pump(token)?;
+      }
+    }
+
+    Ok(())
   }
 
   pub fn init(&mut self) -> io::Result<()> {
@@ -55,18 +384,67 @@ impl Server {
     self
       .poll
       .register(&self.sock, ROOT_TOKEN, Ready::readable(), PollOpt::edge())?;
+    self.poll.register(
+      &self.wake_registration,
+      WAKE_TOKEN,
+      Ready::readable(),
+      PollOpt::edge(),
+    )?;
+    self.install_signal_handler()?;
 
     let mut events = Events::with_capacity(512);
+    let mut draining_since = None;
 
     loop {
-      self.poll.poll(&mut events, None)?;
+      let mut timeout = self
+        .next_deadline()
+        .map(|deadline| deadline.saturating_duration_since(Instant::now()));
+
+      // Once draining, never wait longer than what's left of the grace
+      // period — otherwise a pump whose idle deadline was just refreshed
+      // can hold the shutdown open for up to a full `idle_timeout`.
+      if let Some(since) = draining_since {
+        let remaining = self.shutdown_grace.saturating_sub(since.elapsed());
+        timeout = Some(timeout.map_or(remaining, |t| t.min(remaining)));
+      }
+
+      self.poll.poll(&mut events, timeout)?;
+
+      if draining_since.is_none() && self.draining.load(Ordering::SeqCst) {
+        info!("shutting down listener, draining {} pumps", self.pumps.len());
+        self.poll.deregister(&self.sock)?;
+        draining_since = Some(Instant::now());
+      }
+
+      if self.reload_requested.swap(false, Ordering::SeqCst) {
+        match self.pool.reload(&self.dc_config_path) {
+          Ok(()) => info!("DC endpoint pool reloaded from {:?}", self.dc_config_path),
+          Err(e) => warn!("failed to reload DC endpoint pool: {}", e),
+        }
+      }
+
       self.dispatch(&events)?;
+      self.sweep_idle()?;
       trace!(
         "pumps: {}, links: {}, detached: {}",
         self.pumps.len(),
         self.links.len(),
         self.detached.len()
       );
+
+      if let Some(since) = draining_since {
+        if self.pumps.is_empty() {
+          info!("all connections drained, shutting down");
+          return Ok(());
+        }
+        if since.elapsed() >= self.shutdown_grace {
+          warn!(
+            "shutdown grace period elapsed with {} pumps still open",
+            self.pumps.len()
+          );
+          return Ok(());
+        }
+      }
     }
   }
 
@@ -77,6 +455,10 @@ impl Server {
     for event in events {
       let token = event.token();
 
+      if token == WAKE_TOKEN {
+        continue;
+      }
+
       if token == ROOT_TOKEN {
         trace!("accepting new connection");
         self.accept()?;
@@ -93,14 +475,29 @@ impl Server {
         pump.unwrap().borrow_mut()
       };
 
+      // `pumps` and `conn_ids` are always inserted/removed together, so for
+      // any slab entry that's still present this can't currently fail — it's
+      // defense-in-depth against a future change that lets the two drift
+      // apart (e.g. an insert/remove path that forgets to touch one of them),
+      // not a guard against slot reuse within a single `dispatch` call.
+      match self.conn_ids.get(&token) {
+        Some(&conn_id) if conn_id == pump.conn_id() => {}
+        _ => {
+          warn!("stale event for reused slot: {:?}", token);
+          continue;
+        }
+      }
+
       if readiness.is_readable() {
-        trace!("read event: {:?}", token);
+        trace!("read event: {:?} (conn {})", token, pump.conn_id());
         match pump.drain() {
           Ok(Some(mut dc_idx)) => match self.pool.get(dc_idx) {
             Some(mut peer) => {
+              peer.set_conn_id(Self::next_conn_id(&mut self.next_conn_id));
               let buf = pump.pull();
               if buf.len() > 0 {
                 peer.push(&buf);
+                self.buffered_bytes.set(self.buffered_bytes.get() + buf.len());
               }
               new_peers.insert(token, peer);
             }
@@ -110,54 +507,92 @@ impl Server {
           },
           Ok(_) => {}
           Err(e) => {
-            warn!("drain failed: {:?}: {}", token, e);
+            warn!("drain failed: {:?} (conn {}): {}", token, pump.conn_id(), e);
             stale.insert(token);
           }
         }
         if let Some(peer_token) = self.links.get(&token) {
-          self.fan_out(&mut pump, peer_token)?;
+          self.fan_out(token, &mut pump, peer_token)?;
         }
+
+        Self::touch(
+          &mut self.deadlines,
+          &mut self.timer_wheel,
+          self.idle_timeout,
+          token,
+        );
       }
 
       if readiness.is_writable() {
-        trace!("write event: {:?}", token);
+        trace!("write event: {:?} (conn {})", token, pump.conn_id());
         if let Some(peer_token) = self.links.get(&token) {
-          self.fan_in(&mut pump, peer_token)?;
+          self.fan_in(token, &mut pump, peer_token)?;
         }
+        let queued_before = pump.queued_bytes();
         match pump.flush() {
-          Ok(_) => {}
+          Ok(_) => {
+            let freed = queued_before.saturating_sub(pump.queued_bytes());
+            self
+              .buffered_bytes
+              .set(self.buffered_bytes.get().saturating_sub(freed));
+
+            if pump.queued_bytes() <= LOW_WATERMARK {
+              if let Some(&peer_token) = self.links.get(&token) {
+                self.resume(peer_token)?;
+              }
+            }
+          }
           Err(e) => {
-            warn!("flush failed: {:?}: {}", token, e);
+            warn!("flush failed: {:?} (conn {}): {}", token, pump.conn_id(), e);
             stale.insert(token);
             break;
           }
         }
+
+        Self::touch(
+          &mut self.deadlines,
+          &mut self.timer_wheel,
+          self.idle_timeout,
+          token,
+        );
       }
 
       if readiness.is_hup() {
-        trace!("hup event: {:?}", event.token());
+        trace!("hup event: {:?} (conn {})", event.token(), pump.conn_id());
         stale.insert(token);
       } else if readiness.is_error() {
-        trace!("error event {:?}", event.token());
+        trace!("error event {:?} (conn {})", event.token(), pump.conn_id());
         stale.insert(token);
       } else {
         self.poll.reregister(
           pump.sock(),
           token,
-          pump.interest(),
+          self.effective_interest(token, &pump),
           PollOpt::edge() | PollOpt::oneshot(),
         )?;
       }
     }
 
     for (token, peer_pump) in new_peers {
+      let conn_id = peer_pump.conn_id();
       let idx = self.pumps.insert(RefCell::new(peer_pump));
       let peer_pump = self.pumps.get(idx).unwrap().borrow();
 
       let peer_token = Token(idx);
+      self.conn_ids.insert(peer_token, conn_id);
       self.links.insert(peer_token, token);
       self.links.insert(token, peer_token);
-      info!("linked to dc: {:?} -> {:?}", token, peer_token);
+      info!(
+        "linked to dc: {:?} -> {:?} (conn {})",
+        token, peer_token, conn_id
+      );
+
+      Self::touch(
+        &mut self.deadlines,
+        &mut self.timer_wheel,
+        self.idle_timeout,
+        peer_token,
+      );
 
       self.poll.register(
         peer_pump.sock(),
@@ -183,24 +618,46 @@ impl Server {
   }
 
   fn accept(&mut self) -> io::Result<()> {
+    if self.draining.load(Ordering::SeqCst) {
+      return Ok(());
+    }
+
     if self.pumps.len() > MAX_PUMPS {
       warn!("max connection limit({}) exceeded", MAX_PUMPS / 2);
       return Ok(());
     }
 
-    let sock = match self.sock.accept() {
-      Ok((sock, _)) => sock,
+    if self.buffered_bytes.get() > MAX_BUFFERED_BYTES {
+      warn!(
+        "global buffer cap({}) exceeded, refusing connection",
+        MAX_BUFFERED_BYTES
+      );
+      return Ok(());
+    }
+
+    let (sock, addr) = match self.sock.accept() {
+      Ok(accepted) => accepted,
       Err(err) => {
         warn!("accept failed: {}", err);
         return Ok(());
       }
     };
 
-    let pump = Pump::downstream(&self.secret, sock);
+    let conn_id = Self::next_conn_id(&mut self.next_conn_id);
+    let pump = Pump::downstream(&self.secret, sock, conn_id, self.proxy_protocol, addr);
     let idx = self.pumps.insert(RefCell::new(pump));
-    let pump = self.pumps.get(idx).unwrap().borrow();
 
     let token = Token(idx);
+    self.conn_ids.insert(token, conn_id);
+    Self::touch(
+      &mut self.deadlines,
+      &mut self.timer_wheel,
+      self.idle_timeout,
+      token,
+    );
+
+    let pump = self.pumps.get(idx).unwrap().borrow();
+    info!("accepted {:?} from {:?} (conn {})", token, pump.peer_addr(), conn_id);
 
     self.poll.register(
       pump.sock(),
@@ -212,7 +669,7 @@ impl Server {
     Ok(())
   }
 
-  fn fan_out(&self, pump: &mut Pump, peer_token: &Token) -> io::Result<()> {
+  fn fan_out(&self, token: Token, pump: &mut Pump, peer_token: &Token) -> io::Result<()> {
     trace!("fan out to {:?}", peer_token);
     let buf = pump.pull();
     if buf.is_empty() {
@@ -222,18 +679,34 @@ impl Server {
     let peer = self.pumps.get(peer_token.0).unwrap();
     let mut peer = peer.borrow_mut();
     peer.push(&buf);
+    self.buffered_bytes.set(self.buffered_bytes.get() + buf.len());
 
     self.poll.reregister(
       peer.sock(),
       *peer_token,
-      peer.interest(),
+      self.effective_interest(*peer_token, &peer),
       PollOpt::edge() | PollOpt::oneshot(),
     )?;
 
+    if peer.queued_bytes() >= HIGH_WATERMARK && self.paused.borrow_mut().insert(token) {
+      trace!(
+        "backpressure: pausing {:?}, {:?} has {} bytes queued",
+        token,
+        peer_token,
+        peer.queued_bytes()
+      );
+      self.poll.reregister(
+        pump.sock(),
+        token,
+        pump.interest() - Ready::readable(),
+        PollOpt::edge() | PollOpt::oneshot(),
+      )?;
+    }
+
     Ok(())
   }
 
-  fn fan_in(&self, pump: &mut Pump, peer_token: &Token) -> io::Result<()> {
+  fn fan_in(&self, token: Token, pump: &mut Pump, peer_token: &Token) -> io::Result<()> {
     trace!("fan in from {:?}", peer_token);
     let peer = self.pumps.get(peer_token.0).unwrap();
     let mut peer = peer.borrow_mut();
@@ -243,24 +716,51 @@ impl Server {
       return Ok(());
     }
     pump.push(&buf);
+    self.buffered_bytes.set(self.buffered_bytes.get() + buf.len());
 
     self.poll.reregister(
       peer.sock(),
       *peer_token,
-      peer.interest(),
+      self.effective_interest(*peer_token, &peer),
       PollOpt::edge() | PollOpt::oneshot(),
     )?;
 
+    if pump.queued_bytes() >= HIGH_WATERMARK && self.paused.borrow_mut().insert(*peer_token) {
+      trace!(
+        "backpressure: pausing {:?}, {:?} has {} bytes queued",
+        peer_token,
+        token,
+        pump.queued_bytes()
+      );
+      self.poll.reregister(
+        peer.sock(),
+        *peer_token,
+        peer.interest() - Ready::readable(),
+        PollOpt::edge() | PollOpt::oneshot(),
+      )?;
+    }
+
     Ok(())
   }
 
   fn drop_pump(&mut self, token: Token) -> io::Result<()> {
     self.detached.remove(&token);
+    self.deadlines.remove(&token);
+    self.paused.borrow_mut().remove(&token);
+    let conn_id = self.conn_ids.remove(&token);
 
     let pump = self.pumps.remove(token.0);
     let pump = pump.borrow_mut();
+    self
+      .buffered_bytes
+      .set(self.buffered_bytes.get().saturating_sub(pump.queued_bytes()));
 
-    info!("dropping pump: {:?}", token);
+    info!(
+      "dropping pump: {:?} from {:?} (conn {:?})",
+      token,
+      pump.peer_addr(),
+      conn_id
+    );
     self.poll.deregister(pump.sock())?;
     match self.links.remove(&token) {
       Some(peer_token) => {